@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+
 use nannou::{
     noise::{
         utils::{NoiseMap, NoiseMapBuilder, PlaneMapBuilder},
@@ -6,6 +9,8 @@ use nannou::{
     prelude::*,
 };
 
+use nannou_audio as audio;
+use nannou_audio::Buffer;
 use nannou_egui::{egui, Egui};
 
 const GRID_WIDTH: usize = 120;
@@ -23,12 +28,25 @@ fn main() {
     nannou::app(model).update(update).run();
 }
 
+fn capture(audio: &mut Audio, buffer: &Buffer) {
+    let amp = buffer.iter().fold(0.0f32, |max, &sample| max.max(sample.abs()));
+    let _ = audio.sender.send(amp);
+}
+
 struct Model {
     egui: Egui,
     settings: Settings,
     map: NoiseMap,
     bounds: Rect,
     particles: Vec<Particle>,
+    springs: Vec<Spring>,
+    audio_stream: Option<audio::Stream<Audio>>,
+    audio_rx: mpsc::Receiver<f32>,
+    audio_amp: f32,
+}
+
+struct Audio {
+    sender: mpsc::Sender<f32>,
 }
 
 struct Settings {
@@ -37,11 +55,27 @@ struct Settings {
     draw_background: bool,
     draw_particles: bool,
     draw_flowfield: bool,
+    draw_springs: bool,
+    draw_trails: bool,
+    fade_amount: f32,
     particle_count: usize,
     particle_velocity: f32,
     particle_size: f32,
     particle_steer: f32,
     particle_flow_force: f32,
+    audio_reactive: bool,
+    audio_gain: f32,
+    boids_enabled: bool,
+    cohesion_weight: f32,
+    alignment_weight: f32,
+    separation_weight: f32,
+    neighbor_radius: f32,
+    separation_radius: f32,
+    spring_topology: SpringTopology,
+    spring_stiffness: f32,
+    spring_iterations: usize,
+    color_mode: ColorMode,
+    color_palette: ColorPalette,
 }
 
 struct Particle {
@@ -49,14 +83,97 @@ struct Particle {
     velocity: Vec2,
 }
 
+struct Spring {
+    a: usize,
+    b: usize,
+    rest_length: f32,
+    stiffness: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SpringTopology {
+    None,
+    Chains,
+    Grid,
+}
+
+impl SpringTopology {
+    fn label(&self) -> &'static str {
+        match self {
+            SpringTopology::None => "none",
+            SpringTopology::Chains => "chains",
+            SpringTopology::Grid => "grid",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorMode {
+    Solid,
+    BySpeed,
+    ByAngle,
+}
+
+impl ColorMode {
+    fn label(&self) -> &'static str {
+        match self {
+            ColorMode::Solid => "solid",
+            ColorMode::BySpeed => "by speed",
+            ColorMode::ByAngle => "by angle",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorPalette {
+    Full,
+    BlueToRed,
+    Fire,
+}
+
+impl ColorPalette {
+    fn label(&self) -> &'static str {
+        match self {
+            ColorPalette::Full => "full wheel",
+            ColorPalette::BlueToRed => "blue - red",
+            ColorPalette::Fire => "fire",
+        }
+    }
+
+    /// Hue range, as a 0.0..=1.0 fraction of the color wheel, a normalized
+    /// scalar gets lerped across.
+    fn hue_range(&self) -> (f32, f32) {
+        match self {
+            ColorPalette::Full => (0.0, 1.0),
+            ColorPalette::BlueToRed => (0.66, 1.0),
+            ColorPalette::Fire => (0.0, 0.16),
+        }
+    }
+}
+
 impl Model {
+    /// The noise value at `(x, y)` wrapped into the 0.0..1.0 range (the
+    /// fraction of a full turn) the flow angle and the `ByAngle` color mode
+    /// both derive from.
+    fn sample_angle_fraction(&self, x: f32, y: f32) -> f32 {
+        (self.map.get_value(x as usize, y as usize) as f32).rem_euclid(1.0)
+    }
+
     fn sample_direction(&self, x: f32, y: f32) -> Vec2 {
-        let angle = self.map.get_value(x as usize, y as usize) as f32;
-        let angle = angle * 2.0 * PI;
+        let angle = self.sample_angle_fraction(x, y) * 2.0 * PI;
 
         Vec2::X.rotate(angle)
     }
 
+    /// Maps a normalized scalar `t` (0.0..=1.0) to a color via the selected
+    /// palette's hue range.
+    fn color_for_scalar(&self, t: f32) -> Hsv {
+        let (start, end) = self.settings.color_palette.hue_range();
+        let hue = start + (end - start) * t.clamp(0.0, 1.0);
+
+        hsv(hue, 1.0, 1.0)
+    }
+
     fn generate_map(seed: u32, bounds: &Rect) -> NoiseMap {
         let noise = OpenSimplex::new().set_seed(seed);
         let map = PlaneMapBuilder::new(&noise)
@@ -69,6 +186,190 @@ impl Model {
         map
     }
 
+    /// Builds a uniform spatial hash grid over the current particles so
+    /// neighbor queries only need to look at a particle's cell and its 8
+    /// neighbors instead of the whole population.
+    fn build_spatial_grid(&self, cell_size: f32) -> HashMap<(i32, i32), Vec<usize>> {
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+
+        for (index, particle) in self.particles.iter().enumerate() {
+            let cell = (
+                (particle.position.x / cell_size).floor() as i32,
+                (particle.position.y / cell_size).floor() as i32,
+            );
+            grid.entry(cell).or_default().push(index);
+        }
+
+        grid
+    }
+
+    fn compute_boid_steer(
+        &self,
+        index: usize,
+        grid: &HashMap<(i32, i32), Vec<usize>>,
+        cell_size: f32,
+    ) -> Vec2 {
+        let particle = &self.particles[index];
+        let cell = (
+            (particle.position.x / cell_size).floor() as i32,
+            (particle.position.y / cell_size).floor() as i32,
+        );
+
+        let mut cohesion_sum = Vec2::ZERO;
+        let mut alignment_sum = Vec2::ZERO;
+        let mut separation_sum = Vec2::ZERO;
+        let mut neighbor_count = 0;
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let Some(indices) = grid.get(&(cell.0 + dx, cell.1 + dy)) else {
+                    continue;
+                };
+
+                for &other_index in indices {
+                    if other_index == index {
+                        continue;
+                    }
+
+                    let other = &self.particles[other_index];
+                    let offset = other.position - particle.position;
+                    let distance = offset.length();
+
+                    if distance > self.settings.neighbor_radius || distance <= f32::EPSILON {
+                        continue;
+                    }
+
+                    cohesion_sum += other.position;
+                    alignment_sum += other.velocity;
+                    neighbor_count += 1;
+
+                    if distance < self.settings.separation_radius {
+                        separation_sum -= offset / (distance * distance);
+                    }
+                }
+            }
+        }
+
+        if neighbor_count == 0 {
+            return Vec2::ZERO;
+        }
+
+        let cohesion =
+            (cohesion_sum / neighbor_count as f32 - particle.position).normalize_or_zero();
+        let alignment = (alignment_sum / neighbor_count as f32).normalize_or_zero();
+        let separation = separation_sum.normalize_or_zero();
+
+        cohesion * self.settings.cohesion_weight
+            + alignment * self.settings.alignment_weight
+            + separation * self.settings.separation_weight
+    }
+
+    /// Number of columns used to lay particles out on a grid for the spring
+    /// topologies below, so neighbor indices can be derived from `i % cols`
+    /// and `i / cols` without storing a separate row/col per particle.
+    fn spring_grid_cols(count: usize) -> usize {
+        (count as f32).sqrt().ceil().max(1.0) as usize
+    }
+
+    /// Lays particles out on a regular grid spanning `bounds`, used instead
+    /// of the random scatter when particles are wired together by springs so
+    /// that `rest_length` can be derived from the actual spawn spacing.
+    fn generate_grid_particles(count: usize, bounds: &Rect) -> Vec<Particle> {
+        let cols = Self::spring_grid_cols(count);
+        let rows = (count + cols - 1) / cols.max(1);
+        let origin = bounds.xy();
+        let spacing_x = bounds.w() / cols.max(1) as f32;
+        let spacing_y = bounds.h() / rows.max(1) as f32;
+
+        (0..count)
+            .map(|i| {
+                let col = i % cols;
+                let row = i / cols;
+                let position = origin
+                    + Vec2::X * (col as f32 * spacing_x)
+                    + Vec2::Y * (row as f32 * spacing_y);
+
+                Particle {
+                    position,
+                    velocity: Vec2::ZERO,
+                }
+            })
+            .collect()
+    }
+
+    /// Wires `particles` into springs according to `topology`, treating the
+    /// flat particle list as a grid of `cols` columns (chains connect each
+    /// particle to its right neighbor; grid also connects down and both
+    /// diagonals, like a hanging cloth).
+    fn generate_springs(
+        topology: SpringTopology,
+        particles: &[Particle],
+        cols: usize,
+        stiffness: f32,
+    ) -> Vec<Spring> {
+        let mut springs = Vec::new();
+
+        if topology == SpringTopology::None || cols == 0 {
+            return springs;
+        }
+
+        let mut link = |springs: &mut Vec<Spring>, a: usize, b: usize| {
+            let rest_length = particles[a].position.distance(particles[b].position);
+            springs.push(Spring {
+                a,
+                b,
+                rest_length,
+                stiffness,
+            });
+        };
+
+        for i in 0..particles.len() {
+            let col = i % cols;
+
+            if col + 1 < cols && i + 1 < particles.len() {
+                link(&mut springs, i, i + 1);
+            }
+
+            if topology == SpringTopology::Grid {
+                if i + cols < particles.len() {
+                    link(&mut springs, i, i + cols);
+                }
+
+                if col + 1 < cols && i + cols + 1 < particles.len() {
+                    link(&mut springs, i, i + cols + 1);
+                }
+
+                if col > 0 && i + cols - 1 < particles.len() {
+                    link(&mut springs, i, i + cols - 1);
+                }
+            }
+        }
+
+        springs
+    }
+
+    /// Regenerates particles and springs together so they stay consistent:
+    /// spring topologies need a gridded layout, while plain flow-field mode
+    /// keeps the random scatter.
+    fn rebuild_particles_and_springs(&mut self) {
+        let count = self.settings.particle_count;
+
+        self.particles = match self.settings.spring_topology {
+            SpringTopology::None => Self::generate_particles(count, &self.bounds),
+            SpringTopology::Chains | SpringTopology::Grid => {
+                Self::generate_grid_particles(count, &self.bounds)
+            }
+        };
+
+        let cols = Self::spring_grid_cols(count);
+        self.springs = Self::generate_springs(
+            self.settings.spring_topology,
+            &self.particles,
+            cols,
+            self.settings.spring_stiffness,
+        );
+    }
+
     fn generate_particles(count: usize, bounds: &Rect) -> Vec<Particle> {
         let particles = vec![0; count];
         let particles = particles
@@ -112,11 +413,27 @@ fn model(app: &App) -> Model {
         draw_background: true,
         draw_particles: true,
         draw_flowfield: false,
+        draw_springs: true,
+        draw_trails: false,
+        fade_amount: 0.1,
         particle_count: 400,
         particle_velocity: 1.,
         particle_size: 1.0,
         particle_steer: 0.1,
         particle_flow_force: 1.0,
+        audio_reactive: false,
+        audio_gain: 1.0,
+        boids_enabled: false,
+        cohesion_weight: 0.05,
+        alignment_weight: 0.05,
+        separation_weight: 0.05,
+        neighbor_radius: 20.0,
+        separation_radius: 8.0,
+        spring_topology: SpringTopology::None,
+        spring_stiffness: 0.1,
+        spring_iterations: 1,
+        color_mode: ColorMode::Solid,
+        color_palette: ColorPalette::Full,
     };
 
     let bounds = app
@@ -128,6 +445,37 @@ fn model(app: &App) -> Model {
 
     let map = Model::generate_map(settings.noise_seed, &bounds);
     let particles = Model::generate_particles(settings.particle_count, &bounds);
+    let springs = Model::generate_springs(
+        settings.spring_topology,
+        &particles,
+        Model::spring_grid_cols(settings.particle_count),
+        settings.spring_stiffness,
+    );
+
+    // Audio input isn't guaranteed to be available (no mic, permission
+    // denied, headless/CI boxes), so a failure here just leaves the stream
+    // unset rather than taking down the whole app — audio reactivity simply
+    // has no effect and the noise-only mode keeps working.
+    let audio_host = audio::Host::new();
+    let (sender, audio_rx) = mpsc::channel();
+    let audio_model = Audio { sender };
+    let audio_stream = match audio_host
+        .new_input_stream(audio_model)
+        .capture(capture)
+        .build()
+    {
+        Ok(stream) => match stream.play() {
+            Ok(()) => Some(stream),
+            Err(err) => {
+                eprintln!("failed to start audio input stream: {err}");
+                None
+            }
+        },
+        Err(err) => {
+            eprintln!("failed to build audio input stream: {err}");
+            None
+        }
+    };
 
     Model {
         egui,
@@ -135,6 +483,10 @@ fn model(app: &App) -> Model {
         map,
         bounds,
         particles,
+        springs,
+        audio_stream,
+        audio_rx,
+        audio_amp: 0.0,
     }
 }
 
@@ -164,8 +516,7 @@ fn update(_app: &App, model: &mut Model, update: Update) {
             )
             .changed()
         {
-            model.particles =
-                Model::generate_particles(model.settings.particle_count, &model.bounds);
+            model.rebuild_particles_and_springs();
         }
 
         ui.add(egui::Slider::new(&mut model.settings.particle_size, 0.1..=50.0).text("size"));
@@ -181,6 +532,102 @@ fn update(_app: &App, model: &mut Model, update: Update) {
                 .text("flow force"),
         );
 
+        ui.label("Audio:");
+        ui.add(egui::Checkbox::new(
+            &mut model.settings.audio_reactive,
+            "react to input",
+        ));
+        ui.add(egui::Slider::new(&mut model.settings.audio_gain, 0.0..=10.0).text("gain"));
+
+        ui.label("Boids:");
+        ui.add(egui::Checkbox::new(
+            &mut model.settings.boids_enabled,
+            "flocking",
+        ));
+        ui.add(
+            egui::Slider::new(&mut model.settings.cohesion_weight, 0.0..=1.0).text("cohesion"),
+        );
+        ui.add(
+            egui::Slider::new(&mut model.settings.alignment_weight, 0.0..=1.0).text("alignment"),
+        );
+        ui.add(
+            egui::Slider::new(&mut model.settings.separation_weight, 0.0..=1.0)
+                .text("separation"),
+        );
+        ui.add(
+            egui::Slider::new(&mut model.settings.neighbor_radius, 1.0..=100.0)
+                .text("neighbor radius"),
+        );
+        ui.add(
+            egui::Slider::new(&mut model.settings.separation_radius, 1.0..=100.0)
+                .text("separation radius"),
+        );
+
+        ui.label("Springs:");
+        let mut topology_changed = false;
+        egui::ComboBox::from_label("topology")
+            .selected_text(model.settings.spring_topology.label())
+            .show_ui(ui, |ui| {
+                for topology in [
+                    SpringTopology::None,
+                    SpringTopology::Chains,
+                    SpringTopology::Grid,
+                ] {
+                    if ui
+                        .selectable_value(
+                            &mut model.settings.spring_topology,
+                            topology,
+                            topology.label(),
+                        )
+                        .changed()
+                    {
+                        topology_changed = true;
+                    }
+                }
+            });
+        if topology_changed {
+            model.rebuild_particles_and_springs();
+        }
+
+        if ui
+            .add(
+                egui::Slider::new(&mut model.settings.spring_stiffness, 0.0..=1.0)
+                    .text("stiffness"),
+            )
+            .changed()
+        {
+            let stiffness = model.settings.spring_stiffness;
+            for spring in model.springs.iter_mut() {
+                spring.stiffness = stiffness;
+            }
+        }
+
+        ui.add(
+            egui::Slider::new(&mut model.settings.spring_iterations, 0..=10)
+                .integer()
+                .text("iterations"),
+        );
+
+        ui.label("Color:");
+        egui::ComboBox::from_label("mode")
+            .selected_text(model.settings.color_mode.label())
+            .show_ui(ui, |ui| {
+                for mode in [ColorMode::Solid, ColorMode::BySpeed, ColorMode::ByAngle] {
+                    ui.selectable_value(&mut model.settings.color_mode, mode, mode.label());
+                }
+            });
+        egui::ComboBox::from_label("palette")
+            .selected_text(model.settings.color_palette.label())
+            .show_ui(ui, |ui| {
+                for palette in [ColorPalette::Full, ColorPalette::BlueToRed, ColorPalette::Fire] {
+                    ui.selectable_value(
+                        &mut model.settings.color_palette,
+                        palette,
+                        palette.label(),
+                    );
+                }
+            });
+
         ui.label("Rendering:");
         ui.add(egui::Checkbox::new(
             &mut model.settings.draw_background,
@@ -194,6 +641,9 @@ fn update(_app: &App, model: &mut Model, update: Update) {
             &mut model.settings.draw_flowfield,
             "flowfield",
         ));
+        ui.add(egui::Checkbox::new(&mut model.settings.draw_springs, "springs"));
+        ui.add(egui::Checkbox::new(&mut model.settings.draw_trails, "trails"));
+        ui.add(egui::Slider::new(&mut model.settings.fade_amount, 0.0..=1.0).text("fade amount"));
 
         ui.label("Sim:");
         ui.add(egui::Checkbox::new(&mut model.settings.paused, "Pause"));
@@ -205,19 +655,48 @@ fn update(_app: &App, model: &mut Model, update: Update) {
         return;
     }
 
+    // Drain the audio capture channel, keeping only the latest amplitude, and
+    // low-pass it so a single loud sample doesn't make particles jump.
+    let mut latest_amp = None;
+    while let Ok(amp) = model.audio_rx.try_recv() {
+        latest_amp = Some(amp);
+    }
+    if let Some(amp) = latest_amp {
+        model.audio_amp = model.audio_amp * 0.9 + amp * 0.1;
+    }
+
+    let audio_boost = if model.settings.audio_reactive {
+        1.0 + model.audio_amp * model.settings.audio_gain
+    } else {
+        1.0
+    };
+
     // Update particles
+    let cell_size = model.settings.neighbor_radius.max(1.0);
+    let grid = if model.settings.boids_enabled {
+        Some(model.build_spatial_grid(cell_size))
+    } else {
+        None
+    };
+
     let particles: Vec<Particle> = model
         .particles
         .iter()
-        .map(|particle| {
+        .enumerate()
+        .map(|(index, particle)| {
             let deltatime = update.since_last.as_secs_f32() * 100.;
 
             let direction = model.sample_direction(particle.position.x, particle.position.y);
-            let direction = direction * model.settings.particle_flow_force;
+            let direction = direction * model.settings.particle_flow_force * audio_boost;
 
             let inertia = particle.velocity * (1.0 - model.settings.particle_flow_force);
 
-            let steer = direction + inertia;
+            let boid_steer = match &grid {
+                Some(grid) => model.compute_boid_steer(index, grid, cell_size),
+                None => Vec2::ZERO,
+            };
+
+            let steer = direction + inertia + boid_steer;
             let steer = steer.clamp_length(
                 particle.velocity.length().max(1.0),
                 particle.velocity.length().max(1.0),
@@ -226,8 +705,8 @@ fn update(_app: &App, model: &mut Model, update: Update) {
             let velocity = particle.velocity * (1.0 - model.settings.particle_steer)
                 + steer * model.settings.particle_steer;
             let velocity = velocity.clamp_length(
-                model.settings.particle_velocity,
-                model.settings.particle_velocity,
+                model.settings.particle_velocity * audio_boost,
+                model.settings.particle_velocity * audio_boost,
             );
 
             let mut particle = Particle {
@@ -253,6 +732,31 @@ fn update(_app: &App, model: &mut Model, update: Update) {
         })
         .collect();
 
+    // Relax springs on top of the flow-field step: each iteration nudges the
+    // velocity of both endpoints toward satisfying the rest length.
+    const SPRING_DAMPING: f32 = 0.98;
+
+    let mut particles = particles;
+    for _ in 0..model.settings.spring_iterations {
+        for spring in model.springs.iter() {
+            let pos_a = particles[spring.a].position;
+            let pos_b = particles[spring.b].position;
+            let delta = pos_b - pos_a;
+            let distance = delta.length();
+
+            if distance <= f32::EPSILON {
+                continue;
+            }
+
+            let force = delta / distance * spring.stiffness * (distance - spring.rest_length);
+
+            particles[spring.a].velocity =
+                (particles[spring.a].velocity + force) * SPRING_DAMPING;
+            particles[spring.b].velocity =
+                (particles[spring.b].velocity - force) * SPRING_DAMPING;
+        }
+    }
+
     model.particles = particles;
 }
 
@@ -264,7 +768,13 @@ fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
     let origin = model.bounds.bottom_left();
 
-    if model.settings.draw_background {
+    if model.settings.draw_trails {
+        let (width, height) = model.bounds.w_h();
+        draw.rect()
+            .xy(model.bounds.xy())
+            .w_h(width, height)
+            .color(srgba(0.0, 0.0, 0.0, model.settings.fade_amount));
+    } else if model.settings.draw_background {
         draw.background().color(BLACK);
     }
 
@@ -288,12 +798,40 @@ fn view(app: &App, model: &Model, frame: Frame) {
         }
     }
 
+    if model.settings.draw_springs {
+        for spring in model.springs.iter() {
+            let start = origin + model.particles[spring.a].position;
+            let end = origin + model.particles[spring.b].position;
+
+            draw.line()
+                .start(start)
+                .end(end)
+                .weight(1.0)
+                .color(DARKGRAY);
+        }
+    }
+
     if model.settings.draw_particles {
         for particle in model.particles.iter() {
-            draw.ellipse()
+            let ellipse = draw
+                .ellipse()
                 .xy(origin + particle.position)
-                .w_h(model.settings.particle_size, model.settings.particle_size)
-                .color(PLUM);
+                .w_h(model.settings.particle_size, model.settings.particle_size);
+
+            match model.settings.color_mode {
+                ColorMode::Solid => {
+                    ellipse.color(PLUM);
+                }
+                ColorMode::BySpeed => {
+                    let t = particle.velocity.length()
+                        / model.settings.particle_velocity.max(f32::EPSILON);
+                    ellipse.color(model.color_for_scalar(t));
+                }
+                ColorMode::ByAngle => {
+                    let t = model.sample_angle_fraction(particle.position.x, particle.position.y);
+                    ellipse.color(model.color_for_scalar(t));
+                }
+            }
         }
     }
 